@@ -0,0 +1,149 @@
+//! DDC/EDID read-back over I2C
+//!
+//! Reads the 128-byte base EDID block from the sink over the DDC lines
+//! (7-bit I2C address 0x50, per VESA DDC2B) so the firmware can confirm a
+//! sink actually re-presented a valid EDID after an HPD operation, rather
+//! than just assuming it did because the pulse completed.
+
+use defmt::*;
+use embassy_rp::i2c::{Async, I2c};
+use embassy_rp::peripherals::I2C0;
+
+/// 7-bit DDC/EDID I2C address, fixed by the VESA DDC2B spec
+pub const DDC_ADDRESS: u8 = 0x50;
+
+/// Size of one EDID block (base block or extension block)
+pub const BLOCK_LEN: usize = 128;
+
+/// Expected first 8 bytes of a valid EDID base block
+const EDID_HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+
+/// Offset of the manufacturer ID (2 bytes, 3 packed 5-bit letters)
+const MANUFACTURER_ID_OFFSET: usize = 8;
+
+/// Offset of the extension block count byte
+const EXTENSION_COUNT_OFFSET: usize = 126;
+
+/// Offset of the first (preferred) detailed timing descriptor
+const PREFERRED_TIMING_OFFSET: usize = 54;
+
+/// DDC I2C bus used to read EDID, bound to this board's I2C0 peripheral
+pub type DdcI2c = I2c<'static, I2C0, Async>;
+
+/// Errors that can occur while reading or validating EDID
+#[derive(Clone, Copy, Debug, Format)]
+pub enum EdidError {
+    /// The DDC transaction itself failed (NACK, timeout, bus error)
+    Bus,
+    /// The block didn't start with the fixed EDID header
+    BadHeader,
+    /// The block's checksum byte didn't make the 128-byte sum zero mod 256
+    BadChecksum,
+}
+
+impl From<embassy_rp::i2c::Error> for EdidError {
+    fn from(_: embassy_rp::i2c::Error) -> Self {
+        EdidError::Bus
+    }
+}
+
+/// Manufacturer ID and timing summary extracted from a base EDID block
+#[derive(Clone, Copy, Debug, Format, PartialEq)]
+pub struct EdidSummary {
+    /// 3-letter PNP manufacturer ID (e.g. "DEL", "SAM")
+    pub manufacturer: [u8; 3],
+    /// Preferred timing's active resolution, if the descriptor decodes to one
+    pub preferred_resolution: Option<(u16, u16)>,
+    /// Number of extension blocks the base block declares
+    pub extension_count: u8,
+}
+
+/// Read the base EDID block over `ddc`, validate it, and log a summary
+///
+/// Also attempts to read (and validate) the first declared extension block,
+/// if any, but only logs a warning for it rather than failing the whole read,
+/// since a malformed extension block doesn't make the base block any less
+/// valid. Further extension blocks are not read.
+pub async fn read_and_log(ddc: &mut DdcI2c) -> Result<EdidSummary, EdidError> {
+    let block = read_block(ddc, 0).await?;
+    let summary = summarize(&block);
+
+    info!(
+        "EDID: manufacturer={=[u8]:a} preferred={}x{} extension_blocks={}",
+        &summary.manufacturer,
+        summary.preferred_resolution.map(|(w, _)| w).unwrap_or(0),
+        summary.preferred_resolution.map(|(_, h)| h).unwrap_or(0),
+        summary.extension_count,
+    );
+
+    // E-DDC segment addressing (I2C address 0x30) is needed to reach more
+    // than 2 blocks total; this board only ever checks the one extension
+    // block reachable at offset 0x80 over plain DDC2B.
+    for block_index in 1..=summary.extension_count.min(1) {
+        match read_block(ddc, block_index).await {
+            Ok(ext) if ext.iter().fold(0u8, |acc, &b| acc.wrapping_add(b)) == 0 => {
+                info!("EDID extension block {} checksum OK", block_index);
+            }
+            Ok(_) => warn!("EDID extension block {} failed checksum", block_index),
+            Err(e) => warn!("EDID extension block {} read failed: {:?}", block_index, e),
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Read and validate a single 128-byte EDID block from `ddc`
+///
+/// `block_index` selects which 128-byte block to read: 0 is the base block,
+/// 1 is the first extension block at offset `BLOCK_LEN` (0x80), and so on up
+/// to the 256-byte address space plain DDC2B can reach without E-DDC segment
+/// pointer switching.
+async fn read_block(ddc: &mut DdcI2c, block_index: u8) -> Result<[u8; BLOCK_LEN], EdidError> {
+    let mut block = [0u8; BLOCK_LEN];
+    let offset = block_index * BLOCK_LEN as u8;
+    ddc.write_read_async(DDC_ADDRESS, [offset], &mut block).await?;
+
+    // The fixed header only appears at the start of the base block; extension
+    // blocks instead start with a tag byte, so only validate it there.
+    if block_index == 0 && block[..EDID_HEADER.len()] != EDID_HEADER {
+        return Err(EdidError::BadHeader);
+    }
+
+    let checksum = block.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+    if checksum != 0 {
+        return Err(EdidError::BadChecksum);
+    }
+
+    Ok(block)
+}
+
+fn summarize(block: &[u8; BLOCK_LEN]) -> EdidSummary {
+    EdidSummary {
+        manufacturer: decode_manufacturer(block),
+        preferred_resolution: decode_preferred_resolution(block),
+        extension_count: block[EXTENSION_COUNT_OFFSET],
+    }
+}
+
+/// Decode the 3-letter PNP manufacturer ID packed into 2 big-endian bytes,
+/// 5 bits per letter, offset from 'A' - 1 (per the EDID base spec)
+fn decode_manufacturer(block: &[u8; BLOCK_LEN]) -> [u8; 3] {
+    let packed = u16::from_be_bytes([block[MANUFACTURER_ID_OFFSET], block[MANUFACTURER_ID_OFFSET + 1]]);
+    let letter = |shift: u16| b'A' + (((packed >> shift) & 0x1F) as u8).saturating_sub(1);
+    [letter(10), letter(5), letter(0)]
+}
+
+/// Decode the active horizontal/vertical resolution from the first detailed
+/// timing descriptor, if it's a real timing (nonzero pixel clock) and not
+/// one of the monitor-range/name/etc. descriptor types
+fn decode_preferred_resolution(block: &[u8; BLOCK_LEN]) -> Option<(u16, u16)> {
+    let d = &block[PREFERRED_TIMING_OFFSET..PREFERRED_TIMING_OFFSET + 18];
+    let pixel_clock = u16::from_le_bytes([d[0], d[1]]);
+    if pixel_clock == 0 {
+        return None;
+    }
+
+    let h_active = (d[2] as u16) | (((d[4] as u16) & 0xF0) << 4);
+    let v_active = (d[5] as u16) | (((d[7] as u16) & 0xF0) << 4);
+    Some((h_active, v_active))
+}
@@ -5,8 +5,20 @@
 
 use defmt::*;
 use embassy_rp::gpio::Output;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::watch::Sender;
 use embassy_time::{Duration, Timer};
 
+use crate::edid::{self, DdcI2c};
+
+/// Number of simultaneous subscribers `HpdController` publishes state
+/// changes to (currently `led_indicator` and the serial command interface)
+pub const STATE_WATCH_SUBSCRIBERS: usize = 2;
+
+/// Sender half of the `HpdState` watch a controller publishes to
+pub type StateSender = Sender<'static, ThreadModeRawMutex, HpdState, STATE_WATCH_SUBSCRIBERS>;
+
 /// Timing constants for HPD control
 pub mod timing {
     use embassy_time::Duration;
@@ -43,19 +55,74 @@ pub enum HpdState {
 pub struct HpdController<'a> {
     pin: Output<'a>,
     state: HpdState,
+    ddc: Option<DdcI2c>,
+    state_tx: Option<StateSender>,
 }
 
 impl<'a> HpdController<'a> {
     /// Create a new HPD controller
-    /// 
+    ///
     /// Initially sets HPD to de-asserted (low) state
     pub fn new(pin: Output<'a>) -> Self {
         Self {
             pin,
             state: HpdState::Disconnected,
+            ddc: None,
+            state_tx: None,
         }
     }
-    
+
+    /// Attach a DDC I2C bus so HPD operations can verify the sink's EDID
+    /// re-presented itself (see `reconnect_cycle` and `pulse_duration`)
+    pub fn with_ddc(mut self, ddc: DdcI2c) -> Self {
+        self.ddc = Some(ddc);
+        self
+    }
+
+    /// Attach a watch sender so every state change - including the
+    /// transient `Pulsing` state - is published immediately, not just once
+    /// the operation that caused it has finished
+    pub fn with_state_watch(mut self, state_tx: StateSender) -> Self {
+        self.state_tx = Some(state_tx);
+        self
+    }
+
+    /// Publish the current state to the attached watch, if any
+    fn publish_state(&self) {
+        if let Some(tx) = &self.state_tx {
+            tx.send(self.state);
+        }
+    }
+
+    /// Read the sink's EDID over the attached DDC bus, if any, without
+    /// comparing or drawing any conclusion from it - used to snapshot the
+    /// "before" state ahead of an HPD operation for `check_edid` to diff
+    /// against afterwards
+    async fn read_edid(&mut self) -> Option<edid::EdidSummary> {
+        let ddc = self.ddc.as_mut()?;
+        edid::read_and_log(ddc).await.ok()
+    }
+
+    /// Re-read the sink's EDID after an HPD operation and compare it against
+    /// `before` (as captured by `read_edid` ahead of the operation), logging
+    /// whether the sink re-presented the same EDID or something changed
+    async fn check_edid(&mut self, context: &str, before: Option<edid::EdidSummary>) {
+        let Some(ddc) = self.ddc.as_mut() else {
+            return;
+        };
+
+        match edid::read_and_log(ddc).await {
+            Ok(after) => match before {
+                Some(before) if before == after => {
+                    info!("EDID after {} matches pre-operation read - sink re-presented itself", context)
+                }
+                Some(_) => warn!("EDID after {} differs from the pre-operation read - sink or state may have changed", context),
+                None => info!("EDID check after {}: {:?} (no pre-operation read to compare against)", context, after),
+            },
+            Err(e) => warn!("EDID check after {} failed: {:?}", context, e),
+        }
+    }
+
     /// Get current HPD state
     pub fn state(&self) -> HpdState {
         self.state
@@ -65,13 +132,15 @@ impl<'a> HpdController<'a> {
     pub fn assert(&mut self) {
         self.pin.set_high();
         self.state = HpdState::Connected;
+        self.publish_state();
         info!("HPD asserted (connected)");
     }
-    
+
     /// De-assert HPD (signal that sink is disconnected)
     pub fn deassert(&mut self) {
         self.pin.set_low();
         self.state = HpdState::Disconnected;
+        self.publish_state();
         info!("HPD de-asserted (disconnected)");
     }
     
@@ -97,35 +166,175 @@ impl<'a> HpdController<'a> {
     /// Pulse HPD low for a specific duration
     pub async fn pulse_duration(&mut self, duration: Duration) {
         let was_connected = self.state == HpdState::Connected;
-        
+        let before_edid = self.read_edid().await;
+
         self.state = HpdState::Pulsing;
         self.pin.set_low();
+        self.publish_state();
         info!("HPD pulse started ({} ms)", duration.as_millis());
-        
+
         Timer::after(duration).await;
-        
+
         if was_connected {
             self.pin.set_high();
             self.state = HpdState::Connected;
         } else {
             self.state = HpdState::Disconnected;
         }
-        
+        self.publish_state();
+
         info!("HPD pulse complete");
+        self.check_edid("pulse", before_edid).await;
     }
-    
+
     /// Perform a full disconnect/reconnect cycle
-    /// 
+    ///
     /// This forces the source to completely re-negotiate the connection
     pub async fn reconnect_cycle(&mut self) {
         info!("Starting full reconnect cycle");
-        
+        let before_edid = self.read_edid().await;
+
         self.deassert();
         Timer::after(timing::HPD_PULSE_LONG).await;
-        
+
         self.assert();
         info!("Reconnect cycle complete");
+        self.check_edid("reconnect cycle", before_edid).await;
     }
+
+    /// Run a scripted `HpdSequence`, step by step, bailing out early if a
+    /// higher-priority `HpdCommand` arrives on `interrupt` mid-sequence
+    ///
+    /// Returns the interrupting command, if any, so the caller can decide
+    /// whether to act on it (typically by re-queuing it for the next pass
+    /// through the main command loop).
+    pub async fn run_sequence(
+        &mut self,
+        seq: &HpdSequence,
+        interrupt: &Channel<ThreadModeRawMutex, HpdCommand, 4>,
+    ) -> Option<HpdCommand> {
+        let repeats = seq.repeat.unwrap_or(1);
+        info!("Running HPD sequence ({} step(s), {} repeat(s))", seq.steps.len(), repeats);
+
+        for _ in 0..repeats {
+            for step in seq.steps {
+                if let Ok(cmd) = interrupt.try_receive() {
+                    info!("Sequence interrupted by incoming command");
+                    return Some(cmd);
+                }
+
+                match *step {
+                    HpdStep::Hold(HoldState::Connected, duration) => {
+                        self.assert();
+                        Timer::after(duration).await;
+                    }
+                    HpdStep::Hold(HoldState::Disconnected, duration) => {
+                        self.deassert();
+                        Timer::after(duration).await;
+                    }
+                    HpdStep::Pulse(duration) => {
+                        self.pulse_duration(duration).await;
+                    }
+                }
+            }
+        }
+
+        info!("HPD sequence complete");
+        None
+    }
+}
+
+/// States it makes sense to hold HPD at for a scripted `HpdStep::Hold`
+///
+/// Deliberately narrower than `HpdState`: `Pulsing` is a transient condition
+/// `pulse_duration` reports mid-operation, not something a sequence can hold
+/// at, so it has no place here.
+#[derive(Clone, Copy, Debug, Format)]
+pub enum HoldState {
+    /// Drive and hold HPD asserted (connected)
+    Connected,
+    /// Drive and hold HPD de-asserted (disconnected)
+    Disconnected,
+}
+
+/// One step of a scripted `HpdSequence`
+#[derive(Clone, Copy, Debug, Format)]
+pub enum HpdStep {
+    /// Drive HPD to the given state and hold it there for `Duration`
+    Hold(HoldState, Duration),
+    /// Pulse HPD low for `Duration`, then return to whatever it was before
+    Pulse(Duration),
+}
+
+/// A named, scripted sequence of `HpdStep`s, optionally repeated
+///
+/// Built with `'static` step slices so the built-in patterns in
+/// [`sequences`] (and any board-specific ones) can live in flash as plain
+/// `static`s with no allocation.
+#[derive(Clone, Copy)]
+pub struct HpdSequence {
+    /// Steps run in order, once per repeat
+    pub steps: &'static [HpdStep],
+    /// How many times to run `steps`; `None` means run once
+    pub repeat: Option<u32>,
+}
+
+/// Identifies one of the built-in [`sequences`] patterns, so it can be
+/// carried over the HPD channel (`HpdCommand::RunSequence`) or the serial
+/// interface without shipping the sequence data itself
+#[derive(Clone, Copy, Debug, Format)]
+pub enum SequenceId {
+    /// Repeated short pulses to stress-test EDID re-reads
+    EdidStress,
+    /// A long disconnect followed by reconnect, forcing a full link retrain
+    LinkRetrain,
+    /// 10 connect/disconnect cycles at a fixed 300 ms per state; the period
+    /// is not set at run time
+    FlapTest,
+}
+
+impl SequenceId {
+    /// Look up the built-in `HpdSequence` this ID names
+    pub fn sequence(self) -> &'static HpdSequence {
+        match self {
+            SequenceId::EdidStress => &sequences::EDID_STRESS,
+            SequenceId::LinkRetrain => &sequences::LINK_RETRAIN,
+            SequenceId::FlapTest => &sequences::FLAP_TEST,
+        }
+    }
+}
+
+/// Built-in `HpdSequence` patterns, turning the coupler into a scriptable
+/// HDMI hot-plug stress tester
+pub mod sequences {
+    use embassy_time::Duration;
+
+    use super::{HoldState, HpdSequence, HpdStep};
+
+    /// Repeated short pulses to stress-test EDID re-reads
+    pub static EDID_STRESS: HpdSequence = HpdSequence {
+        steps: &[HpdStep::Pulse(Duration::from_millis(200))],
+        repeat: Some(20),
+    };
+
+    /// A long disconnect followed by reconnect, forcing a full link retrain
+    pub static LINK_RETRAIN: HpdSequence = HpdSequence {
+        steps: &[
+            HpdStep::Hold(HoldState::Disconnected, Duration::from_millis(2000)),
+            HpdStep::Hold(HoldState::Connected, Duration::from_millis(0)),
+        ],
+        repeat: Some(1),
+    };
+
+    /// 10 connect/disconnect cycles holding each state for a fixed 300 ms -
+    /// good for shaking out hot-plug handling bugs in a source
+    pub static FLAP_TEST: HpdSequence = HpdSequence {
+        steps: &[
+            HpdStep::Hold(HoldState::Connected, Duration::from_millis(300)),
+            HpdStep::Hold(HoldState::Disconnected, Duration::from_millis(300)),
+        ],
+        repeat: Some(10),
+    };
 }
 
 /// Commands that can be sent to the HPD controller
@@ -137,8 +346,12 @@ pub enum HpdCommand {
     Deassert,
     /// Toggle current state
     Toggle,
-    /// Pulse HPD for EDID re-read
+    /// Pulse HPD for EDID re-read (recommended duration)
     Pulse,
+    /// Pulse HPD for EDID re-read, for a caller-specified duration
+    PulseFor(Duration),
     /// Full reconnect cycle
     Reconnect,
+    /// Run one of the built-in scripted `HpdSequence` patterns
+    RunSequence(SequenceId),
 }
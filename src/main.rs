@@ -1,34 +1,73 @@
 #![no_std]
 #![no_main]
 
+mod edid;
 mod hpd;
+#[cfg(feature = "dormant-sleep")]
+mod power;
+mod serial;
 
 use defmt::*;
 use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
+use embassy_rp::bind_interrupts;
 use embassy_rp::gpio::{Input, Level, Output, Pull};
+use embassy_rp::i2c::{self, I2c};
+use embassy_rp::peripherals::I2C0;
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
 use embassy_sync::channel::Channel;
-use embassy_time::{Duration, Timer};
+use embassy_sync::signal::Signal;
+use embassy_sync::watch::Watch;
+use embassy_time::{with_timeout, Duration, Timer};
 use {defmt_rtt as _, panic_probe as _};
 
-use hpd::{HpdCommand, HpdController};
+use hpd::{HpdCommand, HpdController, HpdState};
+
+bind_interrupts!(struct I2cIrqs {
+    I2C0_IRQ => i2c::InterruptHandler<I2C0>;
+});
 
 /// GPIO pin assignments based on schematic
 mod pins {
-    
+
     /// HPD_CNTRL - Connected to GPIO20 (directly controls hot plug detection)
     pub const HPD_CNTRL: u8 = 19;
-    
+
     /// GEN_BTN - Connected to GPIO11 (general purpose button)
     pub const GEN_BTN: u8 = 11;
-    
+
     /// GPIO_LED - Connected to GPIO19 (LED indicator)
     pub const GPIO_LED: u8 = 18;
+
+    /// DDC_SDA - Connected to GPIO4 (HDMI DDC/EDID I2C data line)
+    pub const DDC_SDA: u8 = 4;
+
+    /// DDC_SCL - Connected to GPIO5 (HDMI DDC/EDID I2C clock line)
+    pub const DDC_SCL: u8 = 5;
 }
 
 /// Channel for sending HPD commands between tasks
 static HPD_CHANNEL: Channel<ThreadModeRawMutex, HpdCommand, 4> = Channel::new();
 
+/// Broadcasts the current `HpdState` to any interested subscriber (e.g. `led_indicator`)
+static HPD_STATE_WATCH: Watch<ThreadModeRawMutex, HpdState, 2> = Watch::new();
+
+/// Fires once per `HpdCommand` the main loop finishes processing, carrying
+/// the *settled* `HpdState` the command left behind.
+///
+/// `HPD_STATE_WATCH` publishes every intermediate state too (such as the
+/// transient `Pulsing` of a pulse, or the `Disconnected` half of a
+/// `Reconnect`, both of which a `RunSequence` can pass through), which is exactly what `led_indicator`
+/// wants but wrong for a caller that asked "what did my command do" - that
+/// caller needs the state after the command is done, not the first change
+/// it happens to see. `serial` waits on this instead.
+static COMMAND_DONE: Signal<ThreadModeRawMutex, HpdState> = Signal::new();
+
+/// Milliseconds-since-boot timestamp of the last processed `HpdCommand`, used by
+/// the `dormant-sleep` feature to decide when the board has gone idle
+#[cfg(feature = "dormant-sleep")]
+static LAST_COMMAND_MS: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(0);
+
 /// Main entry point
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
@@ -44,35 +83,51 @@ async fn main(spawner: Spawner) {
     // - When GPIO20 is HIGH -> HPD is asserted (sink connected)
     // - When GPIO20 is LOW -> HPD is de-asserted (sink disconnected)
     let hpd_pin = Output::new(p.PIN_20, Level::Low);
-    let mut hpd = HpdController::new(hpd_pin);
-    
+
+    // DDC/EDID I2C bus (GPIO4/GPIO5) for reading back the sink's EDID after
+    // HPD operations
+    let ddc = I2c::new_async(p.I2C0, p.PIN_5, p.PIN_4, I2cIrqs, i2c::Config::default());
+    let mut hpd = HpdController::new(hpd_pin)
+        .with_ddc(ddc)
+        .with_state_watch(HPD_STATE_WATCH.sender());
+
     // Configure the general button as input with pull-up (GPIO11)
     // Button press = LOW (active low)
     let button = Input::new(p.PIN_11, Pull::Up);
-    
+
     // Configure LED as output (GPIO19)
     let led = Output::new(p.PIN_19, Level::Low);
-    
+
     info!("GPIO configured:");
     info!("  - HPD_CNTRL: GPIO{}", pins::HPD_CNTRL);
     info!("  - GEN_BTN:   GPIO{}", pins::GEN_BTN);
     info!("  - LED:       GPIO{}", pins::GPIO_LED);
-    
+    info!("  - DDC_SDA:   GPIO{}", pins::DDC_SDA);
+    info!("  - DDC_SCL:   GPIO{}", pins::DDC_SCL);
+
     // Spawn button handler task
     spawner.spawn(button_handler(button)).unwrap();
     
     // Spawn LED indicator task
-    spawner.spawn(led_indicator(led)).unwrap();
-    
+    let led_state_rx = HPD_STATE_WATCH.receiver().unwrap();
+    spawner.spawn(led_indicator(led, led_state_rx)).unwrap();
+
+    // Spawn the USB CDC-ACM serial command interface
+    let serial_state_rx = HPD_STATE_WATCH.receiver().unwrap();
+    serial::init(spawner, p.USB, serial_state_rx);
+
     // Initial delay before asserting HPD
     info!("Waiting for power stabilization...");
     Timer::after(Duration::from_millis(500)).await;
     
-    // Assert HPD - normal operation (sink connected)
+    // Assert HPD - normal operation (sink connected); publishes to
+    // HPD_STATE_WATCH itself via the sender attached above
     hpd.assert();
     info!("System ready. Press button to toggle HPD.");
-    info!("  - Short press: Toggle HPD state");
-    info!("  - Long press:  HPD pulse (EDID re-read)");
+    info!("  - Short press:  Toggle HPD state");
+    info!("  - Double-click: Full reconnect cycle");
+    info!("  - Triple-click: Run flap-test HPD sequence");
+    info!("  - Long press:   HPD pulse (EDID re-read), repeats while held");
     
     // Main loop - process HPD commands
     loop {
@@ -91,70 +146,171 @@ async fn main(spawner: Spawner) {
             HpdCommand::Pulse => {
                 hpd.pulse().await;
             }
+            HpdCommand::PulseFor(duration) => {
+                hpd.pulse_duration(duration).await;
+            }
             HpdCommand::Reconnect => {
                 hpd.reconnect_cycle().await;
             }
+            HpdCommand::RunSequence(id) => {
+                if let Some(interrupting) = hpd.run_sequence(id.sequence(), &HPD_CHANNEL).await {
+                    // Don't drop the command that interrupted the sequence -
+                    // let the next pass through this loop handle it
+                    let _ = HPD_CHANNEL.try_send(interrupting);
+                }
+            }
         }
-        
+
+        // HpdController already published every state change (including the
+        // transient Pulsing state) to HPD_STATE_WATCH as it happened; this is
+        // the one settled state the command finished on.
         info!("Current HPD state: {:?}", hpd.state());
+        COMMAND_DONE.signal(hpd.state());
+
+        #[cfg(feature = "dormant-sleep")]
+        LAST_COMMAND_MS.store(embassy_time::Instant::now().as_millis(), core::sync::atomic::Ordering::Relaxed);
     }
 }
 
 /// Button handler task
-/// 
-/// Detects button presses and sends appropriate commands:
-/// - Short press (< 500ms): Toggle HPD state
-/// - Long press (>= 500ms): Trigger HPD pulse for EDID re-read
+///
+/// Runs a small gesture state machine over the single general-purpose
+/// button and sends the matching command to `HPD_CHANNEL`:
+/// - Single short press:        Toggle HPD state
+/// - Double-click (a second press starting within `DOUBLE_PRESS_WINDOW` of
+///   a short press's release): Reconnect
+/// - Triple-click (a third press starting within `DOUBLE_PRESS_WINDOW` of
+///   the second press's release): Run the built-in flap-test `HpdSequence`
+/// - Long press (>= `LONG_PRESS_THRESHOLD`): Pulse (EDID re-read)
+/// - Long press held past the threshold: repeated Pulse every
+///   `HOLD_REPEAT_INTERVAL` until release
+///
+/// With the `dormant-sleep` feature enabled, this task also drops the board
+/// into DORMANT sleep once it has been idle past `power::IDLE_TIMEOUT`,
+/// waking on the next button press (see `power::wait_for_press`).
 #[embassy_executor::task]
 async fn button_handler(mut button: Input<'static>) {
     const LONG_PRESS_THRESHOLD: Duration = Duration::from_millis(500);
     const DEBOUNCE: Duration = Duration::from_millis(50);
-    
+    const DOUBLE_PRESS_WINDOW: Duration = Duration::from_millis(350);
+    const HOLD_REPEAT_INTERVAL: Duration = Duration::from_millis(333);
+
     loop {
-        // Wait for button press (falling edge - button is active low)
+        // Wait for button press (falling edge - button is active low). With
+        // the `dormant-sleep` feature enabled, this also drops to DORMANT
+        // sleep once the board has been idle past `power::IDLE_TIMEOUT`,
+        // re-checking idle time on a recurring basis rather than only here.
+        #[cfg(feature = "dormant-sleep")]
+        power::wait_for_press(&mut button, &LAST_COMMAND_MS).await;
+        #[cfg(not(feature = "dormant-sleep"))]
         button.wait_for_falling_edge().await;
+
         Timer::after(DEBOUNCE).await;
-        
+
         // Make sure it's still pressed after debounce
         if button.is_high() {
             continue;
         }
-        
+
         info!("Button pressed");
-        
-        // Measure how long button is held
-        let press_start = embassy_time::Instant::now();
-        
-        // Wait for button release
-        button.wait_for_rising_edge().await;
-        Timer::after(DEBOUNCE).await;
-        
-        let press_duration = press_start.elapsed();
-        
-        if press_duration >= LONG_PRESS_THRESHOLD {
-            // Long press - trigger HPD pulse
-            info!("Long press detected - triggering HPD pulse");
-            HPD_CHANNEL.send(HpdCommand::Pulse).await;
-        } else {
-            // Short press - toggle HPD
-            info!("Short press detected - toggling HPD");
-            HPD_CHANNEL.send(HpdCommand::Toggle).await;
+
+        // Race the long-press threshold against release: if release wins,
+        // this was a short press; if the threshold wins, the button is still
+        // held and we fire the long-press command immediately, then keep
+        // re-firing every HOLD_REPEAT_INTERVAL until release.
+        match with_timeout(LONG_PRESS_THRESHOLD, button.wait_for_rising_edge()).await {
+            Err(_) => {
+                info!("Long press detected - triggering HPD pulse");
+                HPD_CHANNEL.send(HpdCommand::Pulse).await;
+
+                loop {
+                    match select(Timer::after(HOLD_REPEAT_INTERVAL), button.wait_for_rising_edge()).await {
+                        Either::First(()) => {
+                            info!("Hold-repeat - triggering HPD pulse");
+                            HPD_CHANNEL.send(HpdCommand::Pulse).await;
+                        }
+                        Either::Second(()) => break,
+                    }
+                }
+                Timer::after(DEBOUNCE).await;
+            }
+            Ok(()) => {
+                // Short press - wait for a possible second click to form a double-click
+                Timer::after(DEBOUNCE).await;
+                match with_timeout(DOUBLE_PRESS_WINDOW, button.wait_for_falling_edge()).await {
+                    Ok(()) => {
+                        Timer::after(DEBOUNCE).await;
+                        if button.is_low() {
+                            button.wait_for_rising_edge().await;
+                            Timer::after(DEBOUNCE).await;
+
+                            // A third click within the window escalates to the
+                            // flap-test sequence instead of a plain reconnect
+                            match with_timeout(DOUBLE_PRESS_WINDOW, button.wait_for_falling_edge()).await {
+                                Ok(()) => {
+                                    Timer::after(DEBOUNCE).await;
+                                    info!("Triple-click detected - running flap-test sequence");
+                                    HPD_CHANNEL
+                                        .send(HpdCommand::RunSequence(hpd::SequenceId::FlapTest))
+                                        .await;
+                                    button.wait_for_rising_edge().await;
+                                    Timer::after(DEBOUNCE).await;
+                                }
+                                Err(_) => {
+                                    info!("Double-click detected - triggering reconnect");
+                                    HPD_CHANNEL.send(HpdCommand::Reconnect).await;
+                                }
+                            }
+                        } else {
+                            info!("Short press detected - toggling HPD");
+                            HPD_CHANNEL.send(HpdCommand::Toggle).await;
+                        }
+                    }
+                    Err(_) => {
+                        info!("Short press detected - toggling HPD");
+                        HPD_CHANNEL.send(HpdCommand::Toggle).await;
+                    }
+                }
+            }
         }
     }
 }
 
 /// LED indicator task
-/// 
-/// Shows HPD state via LED:
+///
+/// Shows the real `HpdState` as published on `HPD_STATE_WATCH`:
 /// - Solid ON: HPD asserted (connected)
-/// - Blinking: HPD de-asserted (disconnected)
-/// - Fast blink: Processing command
+/// - Blinking (500ms): HPD de-asserted (disconnected)
+/// - Fast blink (100ms): HPD pulse in progress (`Pulsing`)
 #[embassy_executor::task]
-async fn led_indicator(mut led: Output<'static>) {
-    // For now, just blink periodically to show the system is alive
-    // A more sophisticated version would track actual HPD state
+async fn led_indicator(
+    mut led: Output<'static>,
+    mut state_rx: embassy_sync::watch::Receiver<'static, ThreadModeRawMutex, HpdState, 2>,
+) {
+    const BLINK_PERIOD: Duration = Duration::from_millis(500);
+    const FAST_BLINK_PERIOD: Duration = Duration::from_millis(100);
+
+    let mut state = state_rx.get().await;
+
     loop {
-        led.toggle();
-        Timer::after(Duration::from_millis(500)).await;
+        let blink_period = match state {
+            HpdState::Connected => {
+                led.set_high();
+                None
+            }
+            HpdState::Disconnected => Some(BLINK_PERIOD),
+            HpdState::Pulsing => Some(FAST_BLINK_PERIOD),
+        };
+
+        match blink_period {
+            None => {
+                // Solid on - just wait for the next state change
+                state = state_rx.changed().await;
+            }
+            Some(period) => match select(state_rx.changed(), Timer::after(period)).await {
+                Either::First(new_state) => state = new_state,
+                Either::Second(()) => led.toggle(),
+            },
+        }
     }
 }
@@ -0,0 +1,117 @@
+//! DORMANT low-power sleep support (RP2040)
+//!
+//! Opt in with the `dormant-sleep` feature. When enabled, `button_handler`
+//! calls `wait_for_press` in place of a plain `button.wait_for_falling_edge`:
+//! it still resolves as soon as a press comes in, but while waiting it also
+//! re-checks, once per `IDLE_POLL_INTERVAL`, whether `IDLE_TIMEOUT` has
+//! elapsed since the last `HpdCommand` - and if so drops the RP2040 into a
+//! real DORMANT sleep (crystal stopped, core halted) until a falling edge on
+//! `GEN_BTN` wakes it back up. This lets battery- or bus-powered couplers sit
+//! at microamps between button presses or serial commands, not just between
+//! calls to this function.
+//!
+//! The HPD output pin (`HPD_CNTRL`) is a plain pad latch, so its level is
+//! retained across DORMANT for free - `HpdController`'s cached `HpdState`
+//! never actually goes stale and needs no register-level reconciliation,
+//! only a log line to make that explicit on wake.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use defmt::*;
+use embassy_futures::select::{select, Either};
+use embassy_rp::gpio::Input;
+use embassy_time::{Duration, Instant, Timer};
+
+/// How long the command loop may sit idle before dropping to DORMANT
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often to re-check elapsed idle time while waiting for a press, so a
+/// long stretch of silence is noticed without waiting indefinitely on a
+/// single edge future
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Magic value that must be written to XOSC's DORMANT register to actually
+/// request dormant mode (RP2040 datasheet SS2.16.7) - any other value is
+/// ignored, so this isn't a tunable, it's the one value the hardware accepts
+const XOSC_DORMANT_MAGIC: u32 = 0x636f_6d61; // ASCII "coma"
+
+/// Wait for the next button press, dropping to DORMANT sleep while idle
+///
+/// Stands in for `button.wait_for_falling_edge().await`: races that same
+/// edge wait against `IDLE_POLL_INTERVAL`, so elapsed idle time (measured
+/// from `last_command_ms`, milliseconds since boot per
+/// `embassy_time::Instant::as_millis`) is re-evaluated on every tick rather
+/// than once up front. Once `IDLE_TIMEOUT` has elapsed with no press and no
+/// new `HpdCommand`, parks the chip in DORMANT sleep until `button` sees a
+/// falling edge, then returns exactly as if that edge had won the race.
+pub async fn wait_for_press(button: &mut Input<'static>, last_command_ms: &AtomicU64) {
+    loop {
+        let idle_for = Duration::from_millis(Instant::now().as_millis().saturating_sub(last_command_ms.load(Ordering::Relaxed)));
+
+        if idle_for >= IDLE_TIMEOUT {
+            info!("Idle for {} ms - entering DORMANT sleep", idle_for.as_millis());
+
+            button.set_dormant_wake_enabled(true);
+            enter_dormant();
+            button.set_dormant_wake_enabled(false);
+
+            info!("Woke from DORMANT sleep - HPD output held its level via pad latch");
+            return;
+        }
+
+        match select(Timer::after(IDLE_POLL_INTERVAL), button.wait_for_falling_edge()).await {
+            Either::First(()) => continue, // poll tick - loop back and re-check idle time
+            Either::Second(()) => return,  // a real press won the race
+        }
+    }
+}
+
+/// Stop the crystal oscillator and halt the core until the armed GPIO
+/// dormant-wake edge fires, then bring the clock tree back up
+///
+/// Earlier versions of this function only gated peripheral clocks via
+/// CLOCKS.SLEEP_EN0/1 and called WFI - clk_ref/clk_sys and the crystal
+/// driving them never actually stopped, so that was an ordinary (if
+/// slightly deeper) sleep rather than DORMANT, and there was nothing to
+/// restore on wake. This instead drives XOSC into DORMANT directly, which
+/// is what actually stops the crystal, and restores the gated peripheral
+/// clocks once it's confirmed running again.
+///
+/// SAFETY: only called from `wait_for_press` with the button's dormant-wake
+/// edge already armed, and only while every other task is idle waiting on
+/// this one - there is no concurrent access to the clock/oscillator
+/// registers this touches.
+fn enter_dormant() {
+    unsafe {
+        let sleep_en0 = embassy_rp::pac::CLOCKS.sleep_en0().read();
+        let sleep_en1 = embassy_rp::pac::CLOCKS.sleep_en1().read();
+
+        // Nothing needs a running peripheral clock while the crystal itself
+        // is stopped, so gate everything before requesting DORMANT
+        embassy_rp::pac::CLOCKS
+            .sleep_en0()
+            .modify(|w| *w = embassy_rp::pac::clocks::regs::SleepEn0(0));
+        embassy_rp::pac::CLOCKS
+            .sleep_en1()
+            .modify(|w| *w = embassy_rp::pac::clocks::regs::SleepEn1(0));
+
+        cortex_m::asm::dsb();
+
+        // Writing this value to XOSC's DORMANT register stops the crystal;
+        // the core halts as soon as clk_ref/clk_sys stop being driven. On
+        // the armed GPIO edge, XOSC restarts on its own - we just wait for
+        // it to report stable again before touching anything downstream.
+        embassy_rp::pac::XOSC
+            .dormant()
+            .modify(|w| *w = embassy_rp::pac::xosc::regs::Dormant(XOSC_DORMANT_MAGIC));
+
+        cortex_m::asm::wfi();
+
+        while !embassy_rp::pac::XOSC.status().read().stable() {}
+
+        // Restore the peripheral clocks gated above now that the crystal -
+        // and everything derived from it - is running again
+        embassy_rp::pac::CLOCKS.sleep_en0().modify(|w| *w = sleep_en0);
+        embassy_rp::pac::CLOCKS.sleep_en1().modify(|w| *w = sleep_en1);
+    }
+}
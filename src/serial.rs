@@ -0,0 +1,199 @@
+//! USB CDC-ACM serial command interface
+//!
+//! Exposes the same `HpdCommand` set the button produces to a host PC over a
+//! virtual serial port, so the coupler can be driven from a script, a CI
+//! runner, or just a terminal. One command per line (newline-terminated):
+//!
+//!   assert       - Assert HPD (connect)
+//!   deassert     - De-assert HPD (disconnect)
+//!   toggle       - Toggle current HPD state
+//!   pulse [ms]   - Pulse HPD low (default: recommended duration)
+//!   reconnect    - Full disconnect/reconnect cycle
+//!   sequence <name> - Run a built-in HpdSequence (edid-stress|link-retrain|flap-test)
+//!   state        - Query current HpdState
+//!
+//! Every command (including `state`) is answered with the current
+//! `HpdState` once it has been applied, so a host can script a sequence and
+//! confirm each step landed.
+
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_rp::bind_interrupts;
+use embassy_rp::peripherals::USB;
+use embassy_rp::usb::{Driver, InterruptHandler};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::watch::Receiver;
+use embassy_time::Duration;
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::driver::EndpointError;
+use embassy_usb::{Builder, Config, UsbDevice};
+use static_cell::StaticCell;
+
+use crate::hpd::{HpdCommand, HpdState, SequenceId};
+use crate::{COMMAND_DONE, HPD_CHANNEL};
+
+bind_interrupts!(pub struct Irqs {
+    USBCTRL_IRQ => InterruptHandler<USB>;
+});
+
+type UsbDriver = Driver<'static, USB>;
+
+/// Maximum command line length accepted from the host (not counting the line
+/// terminator), and maximum reply line length sent back (including its `\n`)
+const MAX_LINE_LEN: usize = 64;
+
+/// Build the USB CDC-ACM device and spawn the tasks that drive it
+///
+/// Owns the USB peripheral; spawns the USB device poll task and the
+/// line-command parser task that forwards into `HPD_CHANNEL`.
+pub fn init(spawner: Spawner, usb: USB, state_rx: Receiver<'static, ThreadModeRawMutex, HpdState, 2>) {
+    static STATE: StaticCell<State> = StaticCell::new();
+    static CONFIG_DESC: StaticCell<[u8; 256]> = StaticCell::new();
+    static BOS_DESC: StaticCell<[u8; 256]> = StaticCell::new();
+    static CONTROL_BUF: StaticCell<[u8; 64]> = StaticCell::new();
+
+    let driver = Driver::new(usb, Irqs);
+
+    let mut config = Config::new(0xc0de, 0x1337);
+    config.manufacturer = Some("HDMI Coupler");
+    config.product = Some("HPD Control Serial");
+    config.serial_number = Some("HPD-0001");
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+
+    let state = STATE.init(State::new());
+    let config_desc = CONFIG_DESC.init([0; 256]);
+    let bos_desc = BOS_DESC.init([0; 256]);
+    let control_buf = CONTROL_BUF.init([0; 64]);
+
+    let mut builder = Builder::new(driver, config, config_desc, bos_desc, &mut [], control_buf);
+
+    let class = CdcAcmClass::new(&mut builder, state, 64);
+    let usb = builder.build();
+
+    spawner.spawn(usb_task(usb)).unwrap();
+    spawner.spawn(command_task(class, state_rx)).unwrap();
+}
+
+/// Polls the USB device; must run for the host enumeration and transfers to happen
+#[embassy_executor::task]
+async fn usb_task(mut usb: UsbDevice<'static, UsbDriver>) {
+    usb.run().await;
+}
+
+/// Reads newline-terminated commands from the CDC-ACM port, forwards them to
+/// `HPD_CHANNEL`, and echoes back the resulting `HpdState`
+#[embassy_executor::task]
+async fn command_task(
+    mut class: CdcAcmClass<'static, UsbDriver>,
+    mut state_rx: Receiver<'static, ThreadModeRawMutex, HpdState, 2>,
+) {
+    loop {
+        class.wait_connection().await;
+        info!("USB serial host connected");
+
+        if let Err(e) = handle_connection(&mut class, &mut state_rx).await {
+            warn!("USB serial connection ended: {:?}", Debug2Format(&e));
+        }
+
+        info!("USB serial host disconnected");
+    }
+}
+
+async fn handle_connection(
+    class: &mut CdcAcmClass<'static, UsbDriver>,
+    state_rx: &mut Receiver<'static, ThreadModeRawMutex, HpdState, 2>,
+) -> Result<(), EndpointError> {
+    let mut line = [0u8; MAX_LINE_LEN];
+    let mut len = 0usize;
+
+    loop {
+        let mut buf = [0u8; 64];
+        let n = class.read_packet(&mut buf).await?;
+
+        for &byte in &buf[..n] {
+            if byte == b'\n' || byte == b'\r' {
+                if len > 0 {
+                    dispatch(&line[..len], class, state_rx).await?;
+                    len = 0;
+                }
+            } else if len < line.len() {
+                line[len] = byte;
+                len += 1;
+            }
+            // Silently drop characters past MAX_LINE_LEN until the next terminator
+        }
+    }
+}
+
+/// Parses one command line and forwards it to the HPD command loop, then
+/// writes the resulting state back to the host
+async fn dispatch(
+    line: &[u8],
+    class: &mut CdcAcmClass<'static, UsbDriver>,
+    state_rx: &mut Receiver<'static, ThreadModeRawMutex, HpdState, 2>,
+) -> Result<(), EndpointError> {
+    let text = core::str::from_utf8(line).unwrap_or("").trim();
+    let mut parts = text.split_whitespace();
+
+    let command = match parts.next() {
+        Some("assert") => Some(HpdCommand::Assert),
+        Some("deassert") => Some(HpdCommand::Deassert),
+        Some("toggle") => Some(HpdCommand::Toggle),
+        Some("reconnect") => Some(HpdCommand::Reconnect),
+        Some("pulse") => {
+            match parts.next().and_then(|ms| ms.parse::<u64>().ok()) {
+                Some(ms) => Some(HpdCommand::PulseFor(Duration::from_millis(ms))),
+                None => Some(HpdCommand::Pulse),
+            }
+        }
+        Some("sequence") => match parts.next() {
+            Some("edid-stress") => Some(HpdCommand::RunSequence(SequenceId::EdidStress)),
+            Some("link-retrain") => Some(HpdCommand::RunSequence(SequenceId::LinkRetrain)),
+            Some("flap-test") => Some(HpdCommand::RunSequence(SequenceId::FlapTest)),
+            _ => {
+                write_line(class, "ERR unknown sequence (edid-stress|link-retrain|flap-test)").await?;
+                return Ok(());
+            }
+        },
+        Some("state") => None,
+        Some(other) => {
+            warn!("Unrecognized serial command: {}", other);
+            write_line(class, "ERR unrecognized command").await?;
+            return Ok(());
+        }
+        None => return Ok(()),
+    };
+
+    // `state_rx` only ever gives us "what's current" or "what changed next" -
+    // for a multi-step command (reconnect/pulse/sequence) the next change is
+    // often a transient one (Pulsing, or the Disconnected half of a
+    // reconnect), not the state the command actually settles on. COMMAND_DONE fires exactly once per command, after the main loop
+    // has finished processing it, carrying that settled state.
+    let state = if let Some(command) = command {
+        // Discard any stale signal left by a command that finished while we
+        // weren't waiting (e.g. a button press), so `wait()` below can only
+        // pick up the completion of the command we're about to send.
+        COMMAND_DONE.reset();
+        HPD_CHANNEL.send(command).await;
+        COMMAND_DONE.wait().await
+    } else {
+        state_rx.get().await
+    };
+
+    let reply = match state {
+        HpdState::Connected => "OK connected",
+        HpdState::Disconnected => "OK disconnected",
+        HpdState::Pulsing => "OK pulsing",
+    };
+    write_line(class, reply).await
+}
+
+async fn write_line(class: &mut CdcAcmClass<'static, UsbDriver>, text: &str) -> Result<(), EndpointError> {
+    let mut buf = [0u8; MAX_LINE_LEN];
+    let bytes = text.as_bytes();
+    let len = bytes.len().min(buf.len() - 1);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    buf[len] = b'\n';
+    class.write_packet(&buf[..=len]).await
+}